@@ -1,6 +1,6 @@
 use super::Database;
 use super::DatabaseError;
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 
 #[derive(Debug, Clone, serde::Serialize, sqlx::FromRow)]
 pub struct RecordRow {
@@ -113,4 +113,261 @@ impl Database {
             .fetch_all(&lock)
             .await?)
     }
+
+    /// If `record` ended with no content (`add_record` initializes
+    /// `length`/`size` to 0, and nothing was ever written), delete the row
+    /// and its empty work dir rather than leaving a stale entry behind.
+    /// Returns whether the record was pruned.
+    pub async fn prune_if_empty(&self, record: &RecordRow, work_dir: &str) -> Result<bool, DatabaseError> {
+        if record.length != 0 || record.size != 0 {
+            return Ok(false);
+        }
+
+        self.remove_record(record.live_id).await?;
+        if let Err(e) = std::fs::remove_dir_all(work_dir) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                log::warn!("Failed to remove empty work dir {}: {}", work_dir, e);
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// How much a [`RetentionPolicy`] run freed up.
+    pub async fn run_gc(
+        &self,
+        policy: &RetentionPolicy,
+        work_dir_for: impl Fn(&RecordRow) -> String,
+    ) -> Result<GcReport, DatabaseError> {
+        let lock = self.db.read().await.clone().unwrap();
+        let records =
+            sqlx::query_as::<_, RecordRow>("SELECT * FROM records ORDER BY created_at ASC")
+                .fetch_all(&lock)
+                .await?;
+
+        let mut report = GcReport::default();
+        for record in select_records_to_remove(records, policy, Utc::now()) {
+            self.remove_and_account(&record, &work_dir_for, &mut report)
+                .await?;
+        }
+
+        Ok(report)
+    }
+
+    async fn remove_and_account(
+        &self,
+        record: &RecordRow,
+        work_dir_for: &impl Fn(&RecordRow) -> String,
+        report: &mut GcReport,
+    ) -> Result<(), DatabaseError> {
+        let work_dir = work_dir_for(record);
+        self.remove_record(record.live_id).await?;
+        if let Err(e) = std::fs::remove_dir_all(&work_dir) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                log::warn!("Failed to remove work dir {}: {}", work_dir, e);
+            }
+        }
+
+        report.removed_records += 1;
+        report.freed_bytes += record.size as u64;
+        Ok(())
+    }
+}
+
+/// A retention policy for [`Database::run_gc`]. Any combination of limits can
+/// be set; they are applied in turn (age, per-room, total bytes), each to
+/// what the previous ones kept, oldest records first.
+#[derive(Debug, Clone, Default)]
+pub struct RetentionPolicy {
+    /// Keep total recorded bytes (summed `RecordRow::size`) under this budget.
+    pub max_total_bytes: Option<u64>,
+    /// Delete records older than this.
+    pub max_age: Option<std::time::Duration>,
+    /// Keep at most this many records per room.
+    pub max_per_room: Option<usize>,
+}
+
+/// What a [`Database::run_gc`] run freed up.
+#[derive(Debug, Clone, Default)]
+pub struct GcReport {
+    pub removed_records: usize,
+    pub freed_bytes: u64,
+}
+
+/// Pure retention-selection logic behind [`Database::run_gc`]: given every
+/// record and a policy, decide which ones to drop. Each rule is applied
+/// oldest-first in turn (age, then per-room count, then total-bytes budget),
+/// on top of whatever the previous rule already removed, so the total-bytes
+/// budget only has to trim what age/per-room left over. Kept separate from
+/// `run_gc` (and free of any database access) so this interplay can be unit
+/// tested directly.
+fn select_records_to_remove(
+    mut records: Vec<RecordRow>,
+    policy: &RetentionPolicy,
+    now: DateTime<Utc>,
+) -> Vec<RecordRow> {
+    let mut remove = vec![];
+
+    if let Some(max_age) = policy.max_age {
+        let mut keep = vec![];
+        for record in records {
+            let age = now.signed_duration_since(
+                DateTime::parse_from_rfc3339(&record.created_at).unwrap_or(now.into()),
+            );
+            if age.to_std().unwrap_or_default() > max_age {
+                remove.push(record);
+            } else {
+                keep.push(record);
+            }
+        }
+        records = keep;
+    }
+
+    if let Some(max_per_room) = policy.max_per_room {
+        let mut per_room: std::collections::HashMap<u64, Vec<RecordRow>> =
+            std::collections::HashMap::new();
+        for record in records {
+            per_room.entry(record.room_id).or_default().push(record);
+        }
+
+        let mut kept = vec![];
+        for (_, mut room_records) in per_room {
+            // newest first, so the oldest overflow gets dropped
+            room_records.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+            while room_records.len() > max_per_room {
+                remove.push(room_records.pop().unwrap());
+            }
+            kept.extend(room_records);
+        }
+        records = kept;
+    }
+
+    if let Some(max_total_bytes) = policy.max_total_bytes {
+        let mut total: u64 = records.iter().map(|r| r.size as u64).sum();
+        records.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+        for record in records {
+            if total <= max_total_bytes {
+                break;
+            }
+            total = total.saturating_sub(record.size as u64);
+            remove.push(record);
+        }
+    }
+
+    remove
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(live_id: u64, room_id: u64, created_at: &str, size: i64) -> RecordRow {
+        RecordRow {
+            live_id,
+            room_id,
+            title: "title".to_string(),
+            length: 1,
+            size,
+            created_at: created_at.to_string(),
+            cover: None,
+        }
+    }
+
+    #[test]
+    fn max_age_removes_only_old_records() {
+        let now = DateTime::parse_from_rfc3339("2026-01-10T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let records = vec![
+            record(1, 1, "2026-01-01T00:00:00Z", 10), // 9 days old
+            record(2, 1, "2026-01-09T00:00:00Z", 10), // 1 day old
+        ];
+        let policy = RetentionPolicy {
+            max_age: Some(std::time::Duration::from_secs(3 * 24 * 3600)),
+            ..Default::default()
+        };
+
+        let removed = select_records_to_remove(records, &policy, now);
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].live_id, 1);
+    }
+
+    #[test]
+    fn max_per_room_keeps_newest_per_room() {
+        let now = Utc::now();
+        let records = vec![
+            record(1, 1, "2026-01-01T00:00:00Z", 10),
+            record(2, 1, "2026-01-02T00:00:00Z", 10),
+            record(3, 1, "2026-01-03T00:00:00Z", 10),
+            record(4, 2, "2026-01-01T00:00:00Z", 10),
+        ];
+        let policy = RetentionPolicy {
+            max_per_room: Some(2),
+            ..Default::default()
+        };
+
+        let mut removed_ids: Vec<u64> = select_records_to_remove(records, &policy, now)
+            .into_iter()
+            .map(|r| r.live_id)
+            .collect();
+        removed_ids.sort();
+
+        // room 1 has 3 records and a cap of 2, so its oldest is dropped;
+        // room 2 has only 1 record, well under the cap, so nothing is dropped.
+        assert_eq!(removed_ids, vec![1]);
+    }
+
+    #[test]
+    fn max_total_bytes_drops_oldest_until_under_budget() {
+        let now = Utc::now();
+        let records = vec![
+            record(1, 1, "2026-01-01T00:00:00Z", 100),
+            record(2, 1, "2026-01-02T00:00:00Z", 100),
+            record(3, 1, "2026-01-03T00:00:00Z", 100),
+        ];
+        let policy = RetentionPolicy {
+            max_total_bytes: Some(150),
+            ..Default::default()
+        };
+
+        let mut removed_ids: Vec<u64> = select_records_to_remove(records, &policy, now)
+            .into_iter()
+            .map(|r| r.live_id)
+            .collect();
+        removed_ids.sort();
+
+        // 300 total bytes over a 150 budget: drop the oldest first (1), then
+        // re-check (200 still over budget), drop the next oldest (2), which
+        // brings the total to 100 and under budget.
+        assert_eq!(removed_ids, vec![1, 2]);
+    }
+
+    #[test]
+    fn rules_compose_oldest_first_across_all_three() {
+        let now = DateTime::parse_from_rfc3339("2026-01-10T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let records = vec![
+            record(1, 1, "2025-12-01T00:00:00Z", 10), // too old, dropped by max_age
+            record(2, 1, "2026-01-05T00:00:00Z", 10),
+            record(3, 1, "2026-01-06T00:00:00Z", 10),
+            record(4, 1, "2026-01-07T00:00:00Z", 10),
+        ];
+        let policy = RetentionPolicy {
+            max_age: Some(std::time::Duration::from_secs(30 * 24 * 3600)),
+            max_per_room: Some(2),
+            max_total_bytes: Some(15),
+        };
+
+        let mut removed_ids: Vec<u64> = select_records_to_remove(records, &policy, now)
+            .into_iter()
+            .map(|r| r.live_id)
+            .collect();
+        removed_ids.sort();
+
+        // record 1 is removed by max_age; of the remaining three, max_per_room
+        // keeps only the newest two (3, 4), dropping 2; those two hold 20
+        // bytes against a 15-byte budget, so the older of them (3) goes too.
+        assert_eq!(removed_ids, vec![1, 2, 3]);
+    }
 }
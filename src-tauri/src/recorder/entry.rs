@@ -1,15 +1,21 @@
 use core::fmt;
 use std::fmt::Display;
 
-use async_std::{
-    fs::{File, OpenOptions},
-    io::{prelude::BufReadExt, BufReader, WriteExt},
-    path::Path,
-    stream::StreamExt,
-};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_std::stream::Stream;
 use chrono::{TimeZone, Utc};
 
-const ENTRY_FILE_NAME: &str = "entries.log";
+use super::storage::{LocalStorage, SegmentStorage, SegmentWriter};
+
+/// Flush the pending entry buffer once it reaches this many entries, or once
+/// the storage backend's [`SegmentStorage::flush_interval`] has passed since
+/// the last flush, whichever comes first.
+const MAX_BATCH_ENTRIES: usize = 20;
+/// How many times a failed log write is retried, with a growing backoff,
+/// before `flush()` gives up and returns the error.
+const MAX_FLUSH_RETRIES: u32 = 3;
 
 #[derive(Clone)]
 pub struct TsEntry {
@@ -52,10 +58,11 @@ impl TsEntry {
         format!("#EXT-X-PROGRAM-DATE-TIME:{}\n", date_str)
     }
 
-    /// Convert entry into a segment in HLS manifest.
+    /// Convert entry into a segment in HLS manifest, addressed through `url`
+    /// (a storage-resolved path or presigned URL rather than the raw stored key).
     /// If `continuous` is false, DISCONTINUITY and DATE-TIME will be added into tags, so that player can get precise video time for danmaku display.
     /// If `force_time` is true, DATE-TIME will be added into tags which ignores `continuous`.
-    pub fn to_segment(&self, continuous: bool, force_time: bool) -> String {
+    pub fn to_segment(&self, continuous: bool, force_time: bool, url: &str) -> String {
         if self.is_header {
             return "".into();
         }
@@ -70,7 +77,7 @@ impl TsEntry {
             content += &self.date_time();
         }
         content += &format!("#EXTINF:{:.2},\n", self.length);
-        content += &format!("{}\n", self.url);
+        content += &format!("{}\n", url);
 
         content
     }
@@ -89,55 +96,59 @@ impl Display for TsEntry {
 /// EntryStore is used to management stream segments, which is basicly a simple version of hls manifest,
 /// and of course, provids methods to generate hls manifest for frontend player.
 pub struct EntryStore {
-    // append only log file
-    log_file: File,
+    // append only log file, opened through `storage`
+    log_writer: Box<dyn SegmentWriter>,
+    storage: Arc<dyn SegmentStorage>,
     header: Option<TsEntry>,
     entries: Vec<TsEntry>,
     total_duration: f64,
     total_size: u64,
     last_sequence: u64,
 
+    // entries written to `entries` but not yet flushed to `log_writer`
+    pending: Vec<TsEntry>,
+    last_flush: Instant,
+
     pub continue_sequence: u64,
 }
 
 impl EntryStore {
-    pub async fn new(work_dir: &str) -> Self {
-        // if work_dir is not exists, create it
-        if !Path::new(work_dir).exists().await {
-            std::fs::create_dir_all(work_dir).unwrap();
-        }
-        // open append only log file
-        let log_file = OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(format!("{}/{}", work_dir, ENTRY_FILE_NAME))
-            .await
-            .unwrap();
+    /// Open (or create) an entry store backed by the local filesystem at
+    /// `work_dir`. A thin convenience over [`EntryStore::with_storage`] for
+    /// the common case.
+    pub async fn new(work_dir: &str) -> Result<Self, String> {
+        Self::with_storage(Box::new(LocalStorage::new(work_dir))).await
+    }
+
+    /// Open (or create) an entry store backed by an arbitrary
+    /// [`SegmentStorage`], e.g. local disk or S3, loading any existing
+    /// entries from its log.
+    pub async fn with_storage(storage: Box<dyn SegmentStorage>) -> Result<Self, String> {
+        let storage: Arc<dyn SegmentStorage> = Arc::from(storage);
+        let log_writer = storage.open_append().await?;
+
         let mut entry_store = Self {
-            log_file,
+            log_writer,
+            storage,
             header: None,
             entries: vec![],
             total_duration: 0.0,
             total_size: 0,
             last_sequence: 0,
+            pending: vec![],
+            last_flush: Instant::now(),
             continue_sequence: 0,
         };
 
-        entry_store.load(work_dir).await;
+        entry_store.load().await?;
 
-        entry_store
+        Ok(entry_store)
     }
 
-    async fn load(&mut self, work_dir: &str) {
-        let file = OpenOptions::new()
-            .create(false)
-            .read(true)
-            .open(format!("{}/{}", work_dir, ENTRY_FILE_NAME))
-            .await
-            .unwrap();
-        let mut lines = BufReader::new(file).lines();
-        while let Some(Ok(line)) = lines.next().await {
-            let entry = TsEntry::from(&line);
+    async fn load(&mut self) -> Result<(), String> {
+        let content = self.storage.read_log().await?;
+        for line in content.lines() {
+            let entry = TsEntry::from(line);
             if let Err(e) = entry {
                 log::error!("Failed to parse entry: {} {}", e, line);
                 continue;
@@ -160,27 +171,77 @@ impl EntryStore {
         }
 
         self.continue_sequence = self.last_sequence + 100;
+        Ok(())
     }
 
-    pub async fn add_entry(&mut self, entry: TsEntry) {
+    /// Record a new entry and buffer it for the log file. The in-memory
+    /// state (`entries`/`header`/`total_*`) is updated immediately, so
+    /// `manifest()` stays correct even while the buffer hasn't been flushed
+    /// to disk yet. The buffer is drained once it reaches
+    /// [`MAX_BATCH_ENTRIES`] entries or the storage backend's
+    /// [`SegmentStorage::flush_interval`] has elapsed since the last flush,
+    /// whichever comes first.
+    pub async fn add_entry(&mut self, entry: TsEntry) -> Result<(), String> {
         if entry.is_header {
             self.header = Some(entry.clone());
         } else {
             self.entries.push(entry.clone());
         }
 
-        if let Err(e) = self.log_file.write_all(entry.to_string().as_bytes()).await {
-            log::error!("Failed to write entry to log file: {}", e);
-        }
-
-        self.log_file.flush().await.unwrap();
-
         if self.last_sequence < entry.sequence {
             self.last_sequence = entry.sequence;
         }
 
         self.total_duration += entry.length;
         self.total_size += entry.size;
+
+        self.pending.push(entry);
+
+        if self.pending.len() >= MAX_BATCH_ENTRIES
+            || self.last_flush.elapsed() >= self.storage.flush_interval()
+        {
+            self.flush().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Write any buffered entries to the append-only log, retrying with a
+    /// short backoff so a transient disk hiccup doesn't abort the recorder.
+    pub async fn flush(&mut self) -> Result<(), String> {
+        if self.pending.is_empty() {
+            self.last_flush = Instant::now();
+            return Ok(());
+        }
+
+        let mut batch = String::new();
+        for entry in &self.pending {
+            batch += &entry.to_string();
+        }
+
+        let mut attempt = 0;
+        loop {
+            match self.log_writer.write_all(batch.as_bytes()).await {
+                Ok(()) => break,
+                Err(e) if attempt < MAX_FLUSH_RETRIES => {
+                    attempt += 1;
+                    log::warn!(
+                        "Failed to write entries to log file, retrying ({}/{}): {}",
+                        attempt,
+                        MAX_FLUSH_RETRIES,
+                        e
+                    );
+                    async_std::task::sleep(Duration::from_millis(100 * attempt as u64)).await;
+                }
+                Err(e) => return Err(format!("Failed to write entries to log file: {}", e)),
+            }
+        }
+
+        self.log_writer.flush().await?;
+
+        self.pending.clear();
+        self.last_flush = Instant::now();
+        Ok(())
     }
 
     pub fn get_header(&self) -> Option<&TsEntry> {
@@ -207,32 +268,99 @@ impl EntryStore {
         self.entries.first().map(|e| e.ts)
     }
 
-    /// Generate a hls manifest for selected range.
+    /// Generate a hls media playlist for selected range.
     /// `vod` indicates the manifest is for stream or video.
     /// `force_time` adds DATE-TIME tag for each entry.
-    pub fn manifest(&self, vod: bool, force_time: bool, range: Option<Range>) -> String {
-        let mut m3u8_content = "#EXTM3U\n".to_string();
-        m3u8_content += "#EXT-X-VERSION:6\n";
-        m3u8_content += if vod {
-            "#EXT-X-PLAYLIST-TYPE:VOD\n"
+    pub async fn manifest(&self, vod: bool, force_time: bool, range: Option<Range>) -> String {
+        self.manifest_lines(vod, force_time, range).await.concat()
+    }
+
+    /// Generate the media playlist as a lazily-produced stream of lines
+    /// instead of one large `String`. Lines are computed by a background
+    /// task and handed over one at a time through a channel that holds at
+    /// most one line, so a slow consumer (e.g. a hot HTTP response) applies
+    /// backpressure and the whole playlist is never held in memory at once.
+    pub async fn manifest_stream(
+        &self,
+        vod: bool,
+        force_time: bool,
+        range: Option<Range>,
+    ) -> impl Stream<Item = String> {
+        let (tx, rx) = async_std::channel::bounded(1);
+        let opts = ManifestOptions {
+            vod,
+            force_time,
+            range,
+        };
+        let header = self.header.clone();
+        let entries = self.entries.clone();
+        let storage = self.storage.clone();
+        async_std::task::spawn(async move {
+            emit_manifest_lines(opts, header, entries, storage, tx).await;
+        });
+        rx
+    }
+
+    /// Build a master (multivariant) playlist referencing this store's media
+    /// playlist at `media_url`, advertising `subtitle_url` as a selectable
+    /// `SUBTITLES` rendition if a subtitle track exists for this recording.
+    /// `#EXT-X-MEDIA` is only valid in a master playlist per RFC 8216
+    /// §4.3.4.1 ("MUST NOT appear in a Media Playlist"), which is what
+    /// `manifest()`/`manifest_stream()` produce, so the tag belongs here
+    /// instead, alongside `SUBTITLES` on the variant's `#EXT-X-STREAM-INF`.
+    pub fn master_manifest(&self, media_url: &str, subtitle_url: Option<&str>) -> String {
+        let mut out = String::from("#EXTM3U\n#EXT-X-VERSION:6\n");
+
+        if let Some(url) = subtitle_url {
+            out += &format!(
+                "#EXT-X-MEDIA:TYPE=SUBTITLES,GROUP-ID=\"subs\",NAME=\"Generated\",DEFAULT=YES,AUTOSELECT=YES,URI=\"{}\"\n",
+                url
+            );
+        }
+
+        let bandwidth = if self.total_duration > 0.0 {
+            (self.total_size as f64 * 8.0 / self.total_duration) as u64
         } else {
-            "#EXT-X-PLAYLIST-TYPE:EVENT\n"
+            0
         };
-        let end_content = if vod { "#EXT-X-ENDLIST" } else { "" };
+        out += &format!("#EXT-X-STREAM-INF:BANDWIDTH={}", bandwidth);
+        if subtitle_url.is_some() {
+            out += ",SUBTITLES=\"subs\"";
+        }
+        out += &format!("\n{}\n", media_url);
+
+        out
+    }
+
+    async fn manifest_lines(&self, vod: bool, force_time: bool, range: Option<Range>) -> Vec<String> {
+        let mut lines = vec![
+            "#EXTM3U\n".to_string(),
+            "#EXT-X-VERSION:6\n".to_string(),
+            if vod {
+                "#EXT-X-PLAYLIST-TYPE:VOD\n"
+            } else {
+                "#EXT-X-PLAYLIST-TYPE:EVENT\n"
+            }
+            .to_string(),
+        ];
+        let end_content = if vod { "#EXT-X-ENDLIST" } else { "" }.to_string();
 
         if self.entries.is_empty() {
-            m3u8_content += end_content;
-            return m3u8_content;
+            lines.push(end_content);
+            return lines;
         }
 
-        m3u8_content += &format!(
+        lines.push(format!(
             "#EXT-X-TARGETDURATION:{}\n",
             (0.5 + self.entries.first().unwrap().length).floor()
-        );
+        ));
 
         // add header, FMP4 need this
         if let Some(header) = &self.header {
-            m3u8_content += &format!("#EXT-X-MAP:URI=\"{}\"\n", header.url);
+            lines.push(format!(
+                "#EXT-X-MAP:URI=\"{}\"\n",
+                self.storage.resolve_url(&header.url).await
+            ));
         }
 
         let first_entry = self.entries.first().unwrap();
@@ -248,15 +376,122 @@ impl EntryStore {
 
             let entry_offset = (e.ts / 1000 - first_entry_ts) as f32;
             if range.is_none_or(|r| r.is_in(entry_offset)) {
-                m3u8_content += &e.to_segment(!discontinuous, force_time);
+                let url = self.storage.resolve_url(&e.url).await;
+                lines.push(e.to_segment(!discontinuous, force_time, &url));
             }
         }
 
-        m3u8_content += end_content;
-        m3u8_content
+        lines.push(end_content);
+        lines
+    }
+}
+
+impl Drop for EntryStore {
+    // Blocking on `flush()` here would risk stalling whatever executor
+    // happens to be dropping us (this tree also runs Tokio elsewhere), so
+    // Drop only warns; callers that care about the last partial batch should
+    // `.flush().await` explicitly before dropping, e.g. at recorder shutdown.
+    fn drop(&mut self) {
+        if !self.pending.is_empty() {
+            log::warn!(
+                "EntryStore dropped with {} unflushed entries; call flush() before dropping to avoid losing them",
+                self.pending.len()
+            );
+        }
     }
 }
 
+/// Bundles [`EntryStore::manifest_stream`]'s parameters so
+/// [`emit_manifest_lines`] doesn't need to take them individually.
+struct ManifestOptions {
+    vod: bool,
+    force_time: bool,
+    range: Option<Range>,
+}
+
+/// Background half of [`EntryStore::manifest_stream`]: builds the same lines
+/// [`EntryStore::manifest`] would, but sends each one through `tx` as soon as
+/// it's ready instead of collecting them into a `Vec` first. Returns early if
+/// the receiver is gone (the consumer stopped reading).
+async fn emit_manifest_lines(
+    opts: ManifestOptions,
+    header: Option<TsEntry>,
+    entries: Vec<TsEntry>,
+    storage: Arc<dyn SegmentStorage>,
+    tx: async_std::channel::Sender<String>,
+) {
+    let ManifestOptions {
+        vod,
+        force_time,
+        range,
+    } = opts;
+
+    if tx.send("#EXTM3U\n".to_string()).await.is_err() {
+        return;
+    }
+    if tx.send("#EXT-X-VERSION:6\n".to_string()).await.is_err() {
+        return;
+    }
+    let playlist_type = if vod {
+        "#EXT-X-PLAYLIST-TYPE:VOD\n"
+    } else {
+        "#EXT-X-PLAYLIST-TYPE:EVENT\n"
+    };
+    if tx.send(playlist_type.to_string()).await.is_err() {
+        return;
+    }
+
+    let end_content = if vod { "#EXT-X-ENDLIST" } else { "" }.to_string();
+
+    if entries.is_empty() {
+        let _ = tx.send(end_content).await;
+        return;
+    }
+
+    let target_duration = format!(
+        "#EXT-X-TARGETDURATION:{}\n",
+        (0.5 + entries.first().unwrap().length).floor()
+    );
+    if tx.send(target_duration).await.is_err() {
+        return;
+    }
+
+    if let Some(header) = &header {
+        let line = format!(
+            "#EXT-X-MAP:URI=\"{}\"\n",
+            storage.resolve_url(&header.url).await
+        );
+        if tx.send(line).await.is_err() {
+            return;
+        }
+    }
+
+    let first_entry = entries.first().unwrap();
+    let first_entry_ts = first_entry.ts / 1000;
+    let mut previous_seq = first_entry.sequence;
+    for e in &entries {
+        if e.is_header {
+            continue;
+        }
+        let discontinuous = e.sequence < previous_seq || e.sequence - previous_seq > 1;
+        previous_seq = e.sequence;
+
+        let entry_offset = (e.ts / 1000 - first_entry_ts) as f32;
+        if range.is_none_or(|r| r.is_in(entry_offset)) {
+            let url = storage.resolve_url(&e.url).await;
+            if tx
+                .send(e.to_segment(!discontinuous, force_time, &url))
+                .await
+                .is_err()
+            {
+                return;
+            }
+        }
+    }
+
+    let _ = tx.send(end_content).await;
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct Range {
     pub x: f32,
@@ -268,3 +503,465 @@ impl Range {
         v >= self.x && v <= self.y
     }
 }
+
+/// One contiguous piece of an [`ExportFile`], either literal bytes held in
+/// memory (e.g. the fMP4 init segment) or a recorded fragment to be read and
+/// patched lazily from disk.
+enum ExportChunk {
+    Memory(Vec<u8>),
+    Fragment {
+        path: String,
+        size: u64,
+        sequence_number: u32,
+        base_media_decode_time: u64,
+    },
+    /// An MPEG-TS segment, concatenated byte-for-byte. TS segments carry
+    /// their own timing (PCR) per-packet rather than in a container-level
+    /// header, so unlike [`ExportChunk::Fragment`] there's nothing to patch
+    /// before joining them into one continuous stream.
+    Raw { path: String, size: u64 },
+}
+
+impl ExportChunk {
+    fn len(&self) -> u64 {
+        match self {
+            ExportChunk::Memory(data) => data.len() as u64,
+            ExportChunk::Fragment { size, .. } => *size,
+            ExportChunk::Raw { size, .. } => *size,
+        }
+    }
+}
+
+/// A virtual fMP4 (or, for MPEG-TS recordings, concatenated TS) file
+/// assembled from recorded segments, addressable by
+/// byte range so a player can seek (or resume a download) without the whole
+/// recording being materialized in memory or on disk at once.
+///
+/// Built by [`EntryStore::export`]; metadata (init segment / `moov`) always
+/// comes before media data so the result is "fast-start".
+pub struct ExportFile {
+    storage: Arc<dyn SegmentStorage>,
+    chunks: Vec<ExportChunk>,
+    total_size: u64,
+}
+
+impl ExportFile {
+    pub fn total_size(&self) -> u64 {
+        self.total_size
+    }
+
+    /// Read `len` bytes starting at byte `offset` of the virtual file,
+    /// fetching and patching only the segments that overlap the requested
+    /// range so an HTTP `Range` request can be answered lazily.
+    pub async fn read_range(&self, offset: u64, len: u64) -> Result<Vec<u8>, String> {
+        let end = offset + len;
+        let mut out = Vec::with_capacity(len as usize);
+        let mut pos = 0u64;
+
+        for chunk in &self.chunks {
+            let chunk_start = pos;
+            let chunk_end = pos + chunk.len();
+            pos = chunk_end;
+
+            if chunk_end <= offset || chunk_start >= end {
+                continue;
+            }
+
+            let data = match chunk {
+                ExportChunk::Memory(data) => data.clone(),
+                ExportChunk::Fragment {
+                    path,
+                    sequence_number,
+                    base_media_decode_time,
+                    ..
+                } => {
+                    let raw = self.storage.get_segment(path).await?;
+                    rewrite_fragment(&raw, *sequence_number, *base_media_decode_time)?
+                }
+                ExportChunk::Raw { path, .. } => self.storage.get_segment(path).await?,
+            };
+
+            let from = offset.saturating_sub(chunk_start) as usize;
+            let to = (end.min(chunk_end) - chunk_start) as usize;
+            out.extend_from_slice(&data[from..to]);
+        }
+
+        Ok(out)
+    }
+}
+
+/// Find the first top-level ISO-BMFF box of `kind` within `data[start..end]`,
+/// returning its `(start, end)` byte offsets.
+fn find_box(data: &[u8], start: usize, end: usize, kind: &[u8; 4]) -> Option<(usize, usize)> {
+    let mut pos = start;
+    while pos + 8 <= end {
+        let size = u32::from_be_bytes(data[pos..pos + 4].try_into().unwrap()) as usize;
+        if size < 8 || pos + size > end {
+            break;
+        }
+        let box_kind: [u8; 4] = data[pos + 4..pos + 8].try_into().unwrap();
+        if &box_kind == kind {
+            return Some((pos, pos + size));
+        }
+        pos += size;
+    }
+    None
+}
+
+/// Read the track timescale out of an fMP4 init segment's
+/// `moov.trak.mdia.mdhd` box. `tfdt.baseMediaDecodeTime` is expressed in this
+/// timescale (typically 90000 or 48000), not in milliseconds, so callers must
+/// convert wall-clock offsets before writing them into a rewritten fragment.
+fn find_timescale(init: &[u8]) -> Option<u32> {
+    let len = init.len();
+    let (moov_start, moov_end) = find_box(init, 0, len, b"moov")?;
+    let (trak_start, trak_end) = find_box(init, moov_start + 8, moov_end, b"trak")?;
+    let (mdia_start, mdia_end) = find_box(init, trak_start + 8, trak_end, b"mdia")?;
+    let (mdhd_start, _) = find_box(init, mdia_start + 8, mdia_end, b"mdhd")?;
+
+    // mdhd: box header (8) + version/flags (4), then creation_time/modification_time
+    // as u32 pairs (version 0) or u64 pairs (version 1), then timescale (4).
+    let version = init[mdhd_start + 8];
+    let timescale_offset = if version == 1 {
+        mdhd_start + 28
+    } else {
+        mdhd_start + 20
+    };
+
+    Some(u32::from_be_bytes(
+        init[timescale_offset..timescale_offset + 4]
+            .try_into()
+            .ok()?,
+    ))
+}
+
+/// Patch a single fMP4 fragment's `mfhd.sequence_number` and
+/// `tfdt.baseMediaDecodeTime` in place, so a run of fragments recorded
+/// across discontinuities plays back as one continuous movie.
+fn rewrite_fragment(
+    data: &[u8],
+    sequence_number: u32,
+    base_media_decode_time: u64,
+) -> Result<Vec<u8>, String> {
+    let mut out = data.to_vec();
+    let len = out.len();
+
+    let (moof_start, moof_end) =
+        find_box(&out, 0, len, b"moof").ok_or("fragment has no moof box")?;
+
+    if let Some((mfhd_start, _)) = find_box(&out, moof_start + 8, moof_end, b"mfhd") {
+        // mfhd: box header (8) + version/flags (4) + sequence_number (4)
+        out[mfhd_start + 12..mfhd_start + 16].copy_from_slice(&sequence_number.to_be_bytes());
+    }
+
+    if let Some((traf_start, traf_end)) = find_box(&out, moof_start + 8, moof_end, b"traf") {
+        if let Some((tfdt_start, _)) = find_box(&out, traf_start + 8, traf_end, b"tfdt") {
+            let version = out[tfdt_start + 8];
+            if version == 1 {
+                out[tfdt_start + 12..tfdt_start + 20]
+                    .copy_from_slice(&base_media_decode_time.to_be_bytes());
+            } else {
+                out[tfdt_start + 12..tfdt_start + 16]
+                    .copy_from_slice(&(base_media_decode_time as u32).to_be_bytes());
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+impl EntryStore {
+    /// Assemble the recorded segments into a single downloadable MP4/fMP4/TS
+    /// [`ExportFile`], optionally filtered by playback-time `range`.
+    ///
+    /// For fMP4 recordings (an `#EXT-X-MAP` header present) the init segment
+    /// is emitted once, followed by each fragment's `moof`+`mdat`, with
+    /// `sequence_number`/base-media-decode-time rewritten so the result plays
+    /// as one continuous movie. MPEG-TS recordings have no init segment to
+    /// rewrite against, so their segments are concatenated as-is (see
+    /// [`Self::export_ts`]) rather than remuxed into fragmented MP4.
+    pub async fn export(&self, range: Option<Range>) -> Result<ExportFile, String> {
+        if self.entries.is_empty() {
+            return Err("No entries to export".into());
+        }
+
+        match &self.header {
+            Some(header) => self.export_fmp4(header, range).await,
+            None => self.export_ts(range).await,
+        }
+    }
+
+    /// Concatenate a plain MPEG-TS recording's segments byte-for-byte. Valid
+    /// because TS carries its own per-packet timing (PCR) rather than a
+    /// container-level timeline, so segments recorded back-to-back already
+    /// play as one continuous stream once joined; there's no `moov`/`moof`
+    /// structure here for [`rewrite_fragment`] to patch.
+    async fn export_ts(&self, range: Option<Range>) -> Result<ExportFile, String> {
+        let mut total_size = 0u64;
+        let mut chunks = vec![];
+
+        let first_ts = self.entries.first().map(|e| e.ts).unwrap_or(0);
+        for e in &self.entries {
+            let offset = ((e.ts - first_ts) as f64 / 1000.0) as f32;
+            if !range.is_none_or(|r| r.is_in(offset)) {
+                continue;
+            }
+
+            chunks.push(ExportChunk::Raw {
+                path: e.url.clone(),
+                size: e.size,
+            });
+            total_size += e.size;
+        }
+
+        Ok(ExportFile {
+            storage: self.storage.clone(),
+            chunks,
+            total_size,
+        })
+    }
+
+    async fn export_fmp4(&self, header: &TsEntry, range: Option<Range>) -> Result<ExportFile, String> {
+        let init = self.storage.get_segment(&header.url).await?;
+
+        // `tfdt.baseMediaDecodeTime` must be in the track's own timescale, not
+        // milliseconds; fall back to a millisecond timescale (so the old,
+        // already-wrong-but-at-least-consistent behavior is preserved) if the
+        // init segment's mdhd can't be found, which shouldn't happen for a
+        // well-formed fMP4 header.
+        let timescale = find_timescale(&init).unwrap_or_else(|| {
+            log::warn!("Could not find mdhd timescale in fMP4 init segment, assuming 1000");
+            1000
+        }) as u64;
+
+        let mut total_size = init.len() as u64;
+        let mut chunks = vec![ExportChunk::Memory(init)];
+
+        let first_ts = self.entries.first().map(|e| e.ts).unwrap_or(0);
+        let first_entry_ts = first_ts / 1000;
+        let mut sequence_number = 1u32;
+        for e in &self.entries {
+            let offset = (e.ts / 1000 - first_entry_ts) as f32;
+            if !range.is_none_or(|r| r.is_in(offset)) {
+                continue;
+            }
+
+            let offset_ms = (e.ts - first_ts).max(0) as u64;
+            chunks.push(ExportChunk::Fragment {
+                path: e.url.clone(),
+                size: e.size,
+                sequence_number,
+                base_media_decode_time: offset_ms * timescale / 1000,
+            });
+            sequence_number += 1;
+            total_size += e.size;
+        }
+
+        Ok(ExportFile {
+            storage: self.storage.clone(),
+            chunks,
+            total_size,
+        })
+    }
+
+    /// Build a prefetch controller for this store's segments, so a caller
+    /// can warm the storage backend for an upcoming seek before the player
+    /// actually asks for it.
+    pub fn loader_controller(&self) -> StreamLoaderController {
+        StreamLoaderController {
+            storage: self.storage.clone(),
+            entries: self.entries.clone(),
+            first_ts: self.first_ts().unwrap_or(0),
+        }
+    }
+}
+
+/// Pre-warms a store's segment storage for an upcoming playback window
+/// before the player asks for it, translating a seek position into the set
+/// of sequences to prefetch the way `Range::is_in` maps playback time to
+/// entries for the manifest.
+pub struct StreamLoaderController {
+    storage: Arc<dyn SegmentStorage>,
+    entries: Vec<TsEntry>,
+    first_ts: i64,
+}
+
+impl StreamLoaderController {
+    /// Kick off prefetching every segment within playback-time `range`
+    /// without waiting for it to complete.
+    pub fn fetch(&self, range: Range) {
+        let storage = self.storage.clone();
+        let targets = self.targets(range);
+        async_std::task::spawn(async move {
+            for target in targets {
+                if let Err(e) = storage.get_segment(&target).await {
+                    log::warn!("Failed to prefetch segment {}: {}", target, e);
+                }
+            }
+        });
+    }
+
+    /// Prefetch every segment within playback-time `range`, blocking until
+    /// all of them are locally available.
+    pub async fn fetch_blocking(&self, range: Range) -> Result<(), String> {
+        for target in self.targets(range) {
+            self.storage.get_segment(&target).await?;
+        }
+        Ok(())
+    }
+
+    fn targets(&self, range: Range) -> Vec<String> {
+        let first_entry_ts = self.first_ts / 1000;
+        self.entries
+            .iter()
+            .filter(|e| !e.is_header && range.is_in((e.ts / 1000 - first_entry_ts) as f32))
+            .map(|e| e.url.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_box(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(8 + body.len());
+        out.extend_from_slice(&((8 + body.len()) as u32).to_be_bytes());
+        out.extend_from_slice(kind);
+        out.extend_from_slice(body);
+        out
+    }
+
+    fn make_fragment(sequence_number: u32, base_media_decode_time: u32) -> Vec<u8> {
+        let mut mfhd_body = vec![0u8; 4]; // version/flags
+        mfhd_body.extend_from_slice(&sequence_number.to_be_bytes());
+        let mfhd = make_box(b"mfhd", &mfhd_body);
+
+        let mut tfdt_body = vec![0u8; 4]; // version 0, flags 0
+        tfdt_body.extend_from_slice(&base_media_decode_time.to_be_bytes());
+        let tfdt = make_box(b"tfdt", &tfdt_body);
+        let traf = make_box(b"traf", &tfdt);
+
+        let mut moof_body = mfhd;
+        moof_body.extend_from_slice(&traf);
+        make_box(b"moof", &moof_body)
+    }
+
+    fn make_init_segment(timescale: u32) -> Vec<u8> {
+        let mut mdhd_body = vec![0u8; 1 + 3 + 4 + 4]; // version/flags, creation_time, modification_time
+        mdhd_body.extend_from_slice(&timescale.to_be_bytes());
+        mdhd_body.extend_from_slice(&[0u8; 4]); // duration
+        let mdhd = make_box(b"mdhd", &mdhd_body);
+        let mdia = make_box(b"mdia", &mdhd);
+        let trak = make_box(b"trak", &mdia);
+        make_box(b"moov", &trak)
+    }
+
+    #[test]
+    fn rewrite_fragment_patches_sequence_and_decode_time() {
+        let fragment = make_fragment(1, 0);
+        let rewritten = rewrite_fragment(&fragment, 42, 123_456_789).unwrap();
+
+        let (moof_start, moof_end) = find_box(&rewritten, 0, rewritten.len(), b"moof").unwrap();
+        let (mfhd_start, _) = find_box(&rewritten, moof_start + 8, moof_end, b"mfhd").unwrap();
+        assert_eq!(
+            u32::from_be_bytes(rewritten[mfhd_start + 12..mfhd_start + 16].try_into().unwrap()),
+            42
+        );
+
+        let (traf_start, traf_end) = find_box(&rewritten, moof_start + 8, moof_end, b"traf").unwrap();
+        let (tfdt_start, _) = find_box(&rewritten, traf_start + 8, traf_end, b"tfdt").unwrap();
+        assert_eq!(
+            u32::from_be_bytes(rewritten[tfdt_start + 12..tfdt_start + 16].try_into().unwrap()),
+            123_456_789
+        );
+    }
+
+    #[test]
+    fn find_timescale_reads_mdhd() {
+        let init = make_init_segment(90_000);
+        assert_eq!(find_timescale(&init), Some(90_000));
+    }
+
+    /// An in-memory [`SegmentStorage`] double, so `export`/`ExportFile` tests
+    /// don't need a real filesystem or bucket.
+    struct MemoryStorage {
+        segments: std::collections::HashMap<String, Vec<u8>>,
+    }
+
+    struct NoopWriter;
+
+    #[async_trait::async_trait]
+    impl SegmentWriter for NoopWriter {
+        async fn write_all(&mut self, _data: &[u8]) -> Result<(), String> {
+            Ok(())
+        }
+
+        async fn flush(&mut self) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl SegmentStorage for MemoryStorage {
+        async fn open_append(&self) -> Result<Box<dyn SegmentWriter>, String> {
+            Ok(Box::new(NoopWriter))
+        }
+
+        async fn read_log(&self) -> Result<String, String> {
+            Ok(String::new())
+        }
+
+        async fn put_segment(&self, _name: &str, _data: &[u8]) -> Result<(), String> {
+            unimplemented!("not exercised by export tests")
+        }
+
+        async fn get_segment(&self, name: &str) -> Result<Vec<u8>, String> {
+            self.segments
+                .get(name)
+                .cloned()
+                .ok_or_else(|| format!("no such segment: {}", name))
+        }
+
+        async fn list(&self) -> Result<Vec<String>, String> {
+            Ok(self.segments.keys().cloned().collect())
+        }
+
+        async fn resolve_url(&self, name: &str) -> String {
+            name.to_string()
+        }
+    }
+
+    #[test]
+    fn export_ts_concatenates_segments_without_patching() {
+        async_std::task::block_on(async {
+            let segments = std::collections::HashMap::from([
+                ("seg0.ts".to_string(), vec![0u8, 1, 2]),
+                ("seg1.ts".to_string(), vec![3u8, 4, 5, 6]),
+            ]);
+            let mut store = EntryStore::with_storage(Box::new(MemoryStorage { segments }))
+                .await
+                .unwrap();
+
+            for (i, (url, len)) in [("seg0.ts", 3u64), ("seg1.ts", 4u64)].into_iter().enumerate() {
+                store
+                    .add_entry(TsEntry {
+                        url: url.to_string(),
+                        sequence: i as u64,
+                        length: 1.0,
+                        size: len,
+                        ts: i as i64 * 1000,
+                        is_header: false,
+                    })
+                    .await
+                    .unwrap();
+            }
+
+            let export = store.export(None).await.unwrap();
+            assert_eq!(export.total_size(), 7);
+
+            let data = export.read_range(0, 7).await.unwrap();
+            assert_eq!(data, vec![0, 1, 2, 3, 4, 5, 6]);
+        });
+    }
+}
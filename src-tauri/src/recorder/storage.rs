@@ -0,0 +1,431 @@
+use async_std::io::WriteExt;
+use async_std::sync::RwLock;
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::time::Duration;
+
+const ENTRY_FILE_NAME: &str = "entries.log";
+
+/// Default entry-log flush cadence, which `EntryStore` pairs with its
+/// `MAX_BATCH_ENTRIES` count trigger: cheap for backends (local disk) where a
+/// flush is just an append.
+const DEFAULT_FLUSH_INTERVAL: Duration = Duration::from_millis(2000);
+
+/// Cap on [`S3Storage`]'s in-memory prefetch cache so `StreamLoaderController`
+/// warming ahead of playback can't grow it without bound for a long
+/// recording. Evicted oldest-inserted-first once the budget is exceeded.
+const PREFETCH_CACHE_BUDGET_BYTES: u64 = 64 * 1024 * 1024;
+
+/// How often [`S3Storage`]'s entry log is re-uploaded, much wider than the
+/// local-disk default since each upload re-sends the whole log: at the
+/// local-disk cadence a multi-hour recording would transfer O(n^2) total
+/// bytes over its lifetime.
+const S3_FLUSH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Backing store for a recording's append-only entry log and its segment
+/// files. Implemented for the local filesystem and for S3-compatible object
+/// storage, so a finished recording's segments can be tiered off to cheap
+/// remote storage while `entries.log`'s append-only semantics stay the same
+/// from `EntryStore`'s point of view.
+#[async_trait]
+pub trait SegmentStorage: Send + Sync {
+    /// Open (creating if necessary) the entry log for appending, returning a
+    /// writer `EntryStore` can buffer batches of lines into.
+    async fn open_append(&self) -> Result<Box<dyn SegmentWriter>, String>;
+
+    /// Read the full contents of the entry log, or an empty string if it
+    /// doesn't exist yet.
+    async fn read_log(&self) -> Result<String, String>;
+
+    /// Store a segment's bytes under `name`.
+    async fn put_segment(&self, name: &str, data: &[u8]) -> Result<(), String>;
+
+    /// Fetch a segment's bytes by name.
+    async fn get_segment(&self, name: &str) -> Result<Vec<u8>, String>;
+
+    /// List the segment names currently held by this storage backend.
+    async fn list(&self) -> Result<Vec<String>, String>;
+
+    /// Resolve a segment name to the URL a player/exporter should use to
+    /// fetch it: a relative path for local storage, a presigned URL for S3.
+    async fn resolve_url(&self, name: &str) -> String;
+
+    /// How long `EntryStore` should wait between entry-log flushes to this
+    /// backend. A flush re-sends the *whole* entry log for backends with no
+    /// native append (see [`S3Storage`]), so a long recording flushing at
+    /// local-disk cadence would transfer O(n^2) total bytes over its
+    /// lifetime; such backends override this to a much wider interval.
+    fn flush_interval(&self) -> Duration {
+        DEFAULT_FLUSH_INTERVAL
+    }
+}
+
+/// A handle for appending batches of lines to the entry log.
+#[async_trait]
+pub trait SegmentWriter: Send + Sync {
+    async fn write_all(&mut self, data: &[u8]) -> Result<(), String>;
+    async fn flush(&mut self) -> Result<(), String>;
+}
+
+/// Stores `entries.log` and segments directly under `work_dir`, the way
+/// `EntryStore` always has.
+pub struct LocalStorage {
+    work_dir: String,
+}
+
+impl LocalStorage {
+    pub fn new(work_dir: &str) -> Self {
+        LocalStorage {
+            work_dir: work_dir.to_string(),
+        }
+    }
+
+    fn path(&self, name: &str) -> String {
+        format!("{}/{}", self.work_dir, name)
+    }
+}
+
+struct LocalWriter {
+    file: async_std::fs::File,
+}
+
+#[async_trait]
+impl SegmentWriter for LocalWriter {
+    async fn write_all(&mut self, data: &[u8]) -> Result<(), String> {
+        self.file
+            .write_all(data)
+            .await
+            .map_err(|e| format!("Failed to write to local log file: {}", e))
+    }
+
+    async fn flush(&mut self) -> Result<(), String> {
+        self.file
+            .flush()
+            .await
+            .map_err(|e| format!("Failed to flush local log file: {}", e))
+    }
+}
+
+#[async_trait]
+impl SegmentStorage for LocalStorage {
+    async fn open_append(&self) -> Result<Box<dyn SegmentWriter>, String> {
+        if !async_std::path::Path::new(&self.work_dir).exists().await {
+            std::fs::create_dir_all(&self.work_dir)
+                .map_err(|e| format!("Failed to create work dir {}: {}", self.work_dir, e))?;
+        }
+
+        let file = async_std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.path(ENTRY_FILE_NAME))
+            .await
+            .map_err(|e| format!("Failed to open entry log: {}", e))?;
+
+        Ok(Box::new(LocalWriter { file }))
+    }
+
+    async fn read_log(&self) -> Result<String, String> {
+        match async_std::fs::read_to_string(self.path(ENTRY_FILE_NAME)).await {
+            Ok(content) => Ok(content),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(String::new()),
+            Err(e) => Err(format!("Failed to read entry log: {}", e)),
+        }
+    }
+
+    async fn put_segment(&self, name: &str, data: &[u8]) -> Result<(), String> {
+        async_std::fs::write(self.path(name), data)
+            .await
+            .map_err(|e| format!("Failed to write segment {}: {}", name, e))
+    }
+
+    async fn get_segment(&self, name: &str) -> Result<Vec<u8>, String> {
+        async_std::fs::read(self.path(name))
+            .await
+            .map_err(|e| format!("Failed to read segment {}: {}", name, e))
+    }
+
+    async fn list(&self) -> Result<Vec<String>, String> {
+        let mut names = vec![];
+        let mut entries = async_std::fs::read_dir(&self.work_dir)
+            .await
+            .map_err(|e| format!("Failed to list work dir {}: {}", self.work_dir, e))?;
+        while let Some(entry) = async_std::stream::StreamExt::next(&mut entries).await {
+            let entry = entry.map_err(|e| format!("Failed to read dir entry: {}", e))?;
+            if let Some(name) = entry.file_name().to_str() {
+                if name != ENTRY_FILE_NAME {
+                    names.push(name.to_string());
+                }
+            }
+        }
+        Ok(names)
+    }
+
+    async fn resolve_url(&self, name: &str) -> String {
+        name.to_string()
+    }
+}
+
+/// Stores `entries.log` and segments in an S3-compatible bucket under
+/// `prefix`. Segment URLs are resolved to presigned GET URLs so a player can
+/// stream directly from the bucket.
+///
+/// `get_segment` is also backed by a small in-memory cache, so
+/// `StreamLoaderController`'s prefetch (see
+/// [`crate::recorder::entry::StreamLoaderController`]) saves later
+/// `get_segment` callers (e.g. an export read) a round trip to the bucket.
+/// A player following the presigned URLs fetches from the bucket directly
+/// and does not go through this cache.
+pub struct S3Storage {
+    bucket: s3::bucket::Bucket,
+    prefix: String,
+    presign_expiry_secs: u32,
+    cache: RwLock<PrefetchCache>,
+}
+
+impl S3Storage {
+    pub fn new(bucket: s3::bucket::Bucket, prefix: &str) -> Self {
+        S3Storage {
+            bucket,
+            prefix: prefix.trim_end_matches('/').to_string(),
+            presign_expiry_secs: 3600,
+            cache: RwLock::new(PrefetchCache::default()),
+        }
+    }
+
+    fn key(&self, name: &str) -> String {
+        format!("{}/{}", self.prefix, name)
+    }
+}
+
+/// A simple FIFO (not LRU) byte-budgeted cache: good enough for "don't
+/// re-fetch what we just prefetched" without the bookkeeping of real
+/// recency tracking.
+struct PrefetchCache {
+    entries: HashMap<String, std::sync::Arc<Vec<u8>>>,
+    order: std::collections::VecDeque<String>,
+    size: u64,
+    budget: u64,
+}
+
+impl Default for PrefetchCache {
+    fn default() -> Self {
+        PrefetchCache {
+            entries: HashMap::new(),
+            order: std::collections::VecDeque::new(),
+            size: 0,
+            budget: PREFETCH_CACHE_BUDGET_BYTES,
+        }
+    }
+}
+
+impl PrefetchCache {
+    fn get(&self, key: &str) -> Option<std::sync::Arc<Vec<u8>>> {
+        self.entries.get(key).cloned()
+    }
+
+    fn insert(&mut self, key: String, data: std::sync::Arc<Vec<u8>>) {
+        if self.entries.contains_key(&key) {
+            return;
+        }
+
+        self.size += data.len() as u64;
+        self.order.push_back(key.clone());
+        self.entries.insert(key, data);
+
+        while self.size > self.budget {
+            let Some(oldest) = self.order.pop_front() else {
+                break;
+            };
+            if let Some(evicted) = self.entries.remove(&oldest) {
+                self.size = self.size.saturating_sub(evicted.len() as u64);
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl SegmentStorage for S3Storage {
+    async fn open_append(&self) -> Result<Box<dyn SegmentWriter>, String> {
+        Ok(Box::new(S3AppendWriter {
+            bucket: self.bucket.clone(),
+            key: self.key(ENTRY_FILE_NAME),
+        }))
+    }
+
+    async fn read_log(&self) -> Result<String, String> {
+        match self.bucket.get_object(self.key(ENTRY_FILE_NAME)).await {
+            Ok(response) => String::from_utf8(response.to_vec())
+                .map_err(|e| format!("Entry log is not valid utf-8: {}", e)),
+            Err(e) if e.to_string().contains("NoSuchKey") => Ok(String::new()),
+            Err(e) => Err(format!("Failed to read entry log from S3: {}", e)),
+        }
+    }
+
+    async fn put_segment(&self, name: &str, data: &[u8]) -> Result<(), String> {
+        self.bucket
+            .put_object(self.key(name), data)
+            .await
+            .map(|_| ())
+            .map_err(|e| format!("Failed to upload segment {}: {}", name, e))
+    }
+
+    async fn get_segment(&self, name: &str) -> Result<Vec<u8>, String> {
+        if let Some(cached) = self.cache.read().await.get(name) {
+            return Ok((*cached).clone());
+        }
+
+        let data = self
+            .bucket
+            .get_object(self.key(name))
+            .await
+            .map(|response| response.to_vec())
+            .map_err(|e| format!("Failed to fetch segment {}: {}", name, e))?;
+
+        let data = std::sync::Arc::new(data);
+        self.cache
+            .write()
+            .await
+            .insert(name.to_string(), data.clone());
+
+        Ok((*data).clone())
+    }
+
+    async fn list(&self) -> Result<Vec<String>, String> {
+        let results = self
+            .bucket
+            .list(format!("{}/", self.prefix), None)
+            .await
+            .map_err(|e| format!("Failed to list bucket prefix {}: {}", self.prefix, e))?;
+
+        Ok(results
+            .into_iter()
+            .flat_map(|r| r.contents)
+            .filter_map(|obj| obj.key.strip_prefix(&format!("{}/", self.prefix)).map(String::from))
+            .filter(|name| name != ENTRY_FILE_NAME)
+            .collect())
+    }
+
+    async fn resolve_url(&self, name: &str) -> String {
+        self.bucket
+            .presign_get(self.key(name), self.presign_expiry_secs, None)
+            .await
+            .unwrap_or_else(|e| {
+                log::error!("Failed to presign url for {}: {}", name, e);
+                self.key(name)
+            })
+    }
+
+    fn flush_interval(&self) -> Duration {
+        S3_FLUSH_INTERVAL
+    }
+}
+
+/// S3 has no native append; each flush reads the current object (if any),
+/// appends the new batch, and rewrites it. This trades a read-modify-write
+/// per flush for not needing a local staging file, which is acceptable given
+/// flushes are batched at [`SegmentStorage::flush_interval`]'s cadence
+/// rather than [`crate::recorder::entry::EntryStore`]'s local-disk one, which
+/// cuts the number of whole-log re-uploads. Each flush still re-sends the
+/// whole log, so total bytes transferred still grow quadratically with the
+/// recording's length.
+struct S3AppendWriter {
+    bucket: s3::bucket::Bucket,
+    key: String,
+}
+
+#[async_trait]
+impl SegmentWriter for S3AppendWriter {
+    async fn write_all(&mut self, data: &[u8]) -> Result<(), String> {
+        let mut existing = match self.bucket.get_object(&self.key).await {
+            Ok(response) => response.to_vec(),
+            Err(e) if e.to_string().contains("NoSuchKey") => vec![],
+            Err(e) => return Err(format!("Failed to read entry log for append: {}", e)),
+        };
+
+        existing.extend_from_slice(data);
+
+        self.bucket
+            .put_object(&self.key, &existing)
+            .await
+            .map(|_| ())
+            .map_err(|e| format!("Failed to write entry log to S3: {}", e))
+    }
+
+    async fn flush(&mut self) -> Result<(), String> {
+        // put_object in write_all is already durable; nothing buffered locally.
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry_of_size(n: usize) -> std::sync::Arc<Vec<u8>> {
+        std::sync::Arc::new(vec![0u8; n])
+    }
+
+    #[test]
+    fn prefetch_cache_returns_what_it_holds() {
+        let mut cache = PrefetchCache {
+            budget: 100,
+            ..Default::default()
+        };
+        cache.insert("a".to_string(), entry_of_size(10));
+        assert_eq!(cache.get("a").map(|d| d.len()), Some(10));
+        assert!(cache.get("missing").is_none());
+    }
+
+    #[test]
+    fn prefetch_cache_does_not_duplicate_or_double_count_a_reinserted_key() {
+        let mut cache = PrefetchCache {
+            budget: 100,
+            ..Default::default()
+        };
+        cache.insert("a".to_string(), entry_of_size(10));
+        cache.insert("a".to_string(), entry_of_size(20));
+        assert_eq!(cache.size, 10);
+    }
+
+    #[test]
+    fn prefetch_cache_evicts_oldest_first_once_over_budget() {
+        let mut cache = PrefetchCache {
+            budget: 25,
+            ..Default::default()
+        };
+        cache.insert("a".to_string(), entry_of_size(10));
+        cache.insert("b".to_string(), entry_of_size(10));
+        // Pushes total to 30, over the 25 budget: "a" (oldest) is evicted,
+        // bringing it back down to 20.
+        cache.insert("c".to_string(), entry_of_size(10));
+
+        assert!(cache.get("a").is_none());
+        assert!(cache.get("b").is_some());
+        assert!(cache.get("c").is_some());
+        assert_eq!(cache.size, 20);
+    }
+
+    #[test]
+    fn prefetch_cache_evicts_as_many_entries_as_needed_to_get_under_budget() {
+        let mut cache = PrefetchCache {
+            budget: 25,
+            ..Default::default()
+        };
+        cache.insert("a".to_string(), entry_of_size(10));
+        cache.insert("b".to_string(), entry_of_size(10));
+        // A 20-byte entry pushes the total to 40: evicting "a" alone leaves
+        // 30, still over the 25 budget, so "b" must go too.
+        cache.insert("c".to_string(), entry_of_size(20));
+
+        assert!(cache.get("a").is_none());
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("c").is_some());
+        assert_eq!(cache.size, 20);
+    }
+
+    #[test]
+    fn local_storage_uses_the_default_flush_interval() {
+        let storage = LocalStorage::new("/tmp/shadowreplay-test");
+        assert_eq!(storage.flush_interval(), DEFAULT_FLUSH_INTERVAL);
+    }
+}
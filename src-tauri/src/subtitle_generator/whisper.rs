@@ -8,13 +8,33 @@ use whisper_rs::{FullParams, SamplingStrategy, WhisperContext, WhisperContextPar
 
 use super::SubtitleGenerator;
 
+/// Target integrated loudness (LUFS) that [`normalize_loudness`] gains samples towards.
+const TARGET_LUFS: f64 = -16.0;
+/// RNNoise/Whisper operate at these sample rates; denoising happens at 48 kHz and
+/// Whisper expects 16 kHz, so audio is resampled up and back down around it.
+const DENOISE_SAMPLE_RATE: u32 = 48_000;
+const WHISPER_SAMPLE_RATE: u32 = 16_000;
+
 #[derive(Clone)]
 pub struct WhisperCPP {
     ctx: Arc<RwLock<WhisperContext>>,
     prompt: String,
+    /// Apply EBU R128 loudness normalization to the decoded audio before transcription.
+    normalize: bool,
+    /// Apply RNNoise speech denoising to the decoded audio before transcription.
+    denoise: bool,
 }
 
 pub async fn new(model: &Path, prompt: &str) -> Result<WhisperCPP, String> {
+    new_with_options(model, prompt, false, false).await
+}
+
+pub async fn new_with_options(
+    model: &Path,
+    prompt: &str,
+    normalize: bool,
+    denoise: bool,
+) -> Result<WhisperCPP, String> {
     let ctx = WhisperContext::new_with_params(
         model.to_str().unwrap(),
         WhisperContextParameters::default(),
@@ -24,16 +44,104 @@ pub async fn new(model: &Path, prompt: &str) -> Result<WhisperCPP, String> {
     Ok(WhisperCPP {
         ctx: Arc::new(RwLock::new(ctx)),
         prompt: prompt.to_string(),
+        normalize,
+        denoise,
     })
 }
 
+/// Measure EBU R128 integrated loudness (LUFS) and apply a linear gain
+/// towards [`TARGET_LUFS`], clamping the gain so peaks don't clip.
+fn normalize_loudness(samples: &mut [f32], sample_rate: u32) -> Result<(), String> {
+    let mut meter =
+        ebur128::EbuR128::new(1, sample_rate, ebur128::Mode::I).map_err(|e| e.to_string())?;
+    meter.add_frames_f32(samples).map_err(|e| e.to_string())?;
+
+    let measured = meter.loudness_global().map_err(|e| e.to_string())?;
+    if !measured.is_finite() {
+        // silence or too short to measure; nothing to normalize.
+        return Ok(());
+    }
+
+    let mut gain = 10f32.powf(((TARGET_LUFS - measured) / 20.0) as f32);
+    let peak = samples.iter().fold(0f32, |max, &s| max.max(s.abs()));
+    if peak > 0.0 {
+        gain = gain.min(0.99 / peak);
+    }
+
+    for sample in samples.iter_mut() {
+        *sample *= gain;
+    }
+
+    Ok(())
+}
+
+/// Scale factor between the `[-1.0, 1.0]` normalized floats the rest of this
+/// module works in and the 16-bit-PCM-range floats RNNoise expects as input
+/// and produces as output.
+const RNNOISE_PCM_SCALE: f32 = i16::MAX as f32;
+
+/// Suppress background noise with RNNoise, processing 480-sample (10ms)
+/// frames at 48 kHz as the denoiser requires.
+fn denoise_samples(samples: &[f32], sample_rate: u32) -> Result<Vec<f32>, String> {
+    let resampled = resample(samples, sample_rate, DENOISE_SAMPLE_RATE);
+
+    let mut denoiser = nnnoiseless::DenoiseState::new();
+    let mut out = vec![0f32; resampled.len()];
+    let mut frame = vec![0f32; nnnoiseless::FRAME_SIZE];
+    let mut scratch = vec![0f32; nnnoiseless::FRAME_SIZE];
+    for (chunk_in, chunk_out) in resampled
+        .chunks(nnnoiseless::FRAME_SIZE)
+        .zip(out.chunks_mut(nnnoiseless::FRAME_SIZE))
+    {
+        frame[..chunk_in.len()].copy_from_slice(chunk_in);
+        frame[chunk_in.len()..].fill(0.0);
+        for sample in frame.iter_mut() {
+            *sample *= RNNOISE_PCM_SCALE;
+        }
+        denoiser.process_frame(&mut scratch, &frame);
+        chunk_out.copy_from_slice(&scratch[..chunk_out.len()]);
+        for sample in chunk_out.iter_mut() {
+            *sample /= RNNOISE_PCM_SCALE;
+        }
+    }
+
+    Ok(resample(&out, DENOISE_SAMPLE_RATE, sample_rate))
+}
+
+/// Naive linear-interpolation resampler, good enough for the short hops
+/// between Whisper's 16 kHz input and RNNoise's 48 kHz requirement.
+fn resample(samples: &[f32], from_rate: u32, to_rate: u32) -> Vec<f32> {
+    if from_rate == to_rate || samples.is_empty() {
+        return samples.to_vec();
+    }
+
+    let ratio = to_rate as f64 / from_rate as f64;
+    let out_len = (samples.len() as f64 * ratio).round() as usize;
+    (0..out_len)
+        .map(|i| {
+            let src_pos = i as f64 / ratio;
+            let idx = src_pos.floor() as usize;
+            let frac = (src_pos - idx as f64) as f32;
+            let a = samples[idx.min(samples.len() - 1)];
+            let b = samples[(idx + 1).min(samples.len() - 1)];
+            a + (b - a) * frac
+        })
+        .collect()
+}
+
 #[async_trait]
 impl SubtitleGenerator for WhisperCPP {
+    /// `segment_start_secs` is `audio_path`'s offset from the start of the
+    /// recording (i.e. from its first `#EXT-X-PROGRAM-DATE-TIME` anchor);
+    /// cue timestamps are shifted by it so subtitles generated from any one
+    /// clip still land on the right point in the full stream's timeline
+    /// rather than restarting from zero for every clip.
     async fn generate_subtitle(
         &self,
         reporter: &impl ProgressReporterTrait,
         audio_path: &Path,
         output_path: &Path,
+        segment_start_secs: f64,
     ) -> Result<String, String> {
         log::info!("Generating subtitle for {:?}", audio_path);
         let start_time = std::time::Instant::now();
@@ -76,7 +184,17 @@ impl SubtitleGenerator for WhisperCPP {
             return Err(e.to_string());
         }
 
-        let samples = samples.unwrap();
+        let mut samples = samples.unwrap();
+
+        if self.normalize || self.denoise {
+            reporter.update("预处理音频中");
+        }
+        if self.denoise {
+            samples = denoise_samples(&samples, WHISPER_SAMPLE_RATE)?;
+        }
+        if self.normalize {
+            normalize_loudness(&mut samples, WHISPER_SAMPLE_RATE)?;
+        }
 
         reporter.update("生成字幕中");
         if let Err(e) = state.full(params, &samples[..]) {
@@ -89,9 +207,19 @@ impl SubtitleGenerator for WhisperCPP {
             log::error!("failed to create output file: {}", e);
             e.to_string()
         })?;
+        // WebVTT only differs from SRT in its header, the lack of a cue
+        // index, and a '.' instead of ',' as the fractional-seconds separator.
+        let is_vtt = output_path
+            .extension()
+            .is_some_and(|ext| ext == "vtt");
+
         // fetch the results
         let num_segments = state.full_n_segments().map_err(|e| e.to_string())?;
-        let mut subtitle = String::new();
+        let mut subtitle = if is_vtt {
+            "WEBVTT\n\n".to_string()
+        } else {
+            String::new()
+        };
         for i in 0..num_segments {
             let segment = state.full_get_segment_text(i).map_err(|e| e.to_string())?;
             let start_timestamp = state.full_get_segment_t0(i).map_err(|e| e.to_string())?;
@@ -101,16 +229,33 @@ impl SubtitleGenerator for WhisperCPP {
                 let hours = (timestamp / 3600.0).floor();
                 let minutes = ((timestamp - hours * 3600.0) / 60.0).floor();
                 let seconds = timestamp - hours * 3600.0 - minutes * 60.0;
-                format!("{:02}:{:02}:{:06.3}", hours, minutes, seconds).replace(".", ",")
+                let formatted = format!("{:02}:{:02}:{:06.3}", hours, minutes, seconds);
+                if is_vtt {
+                    formatted
+                } else {
+                    formatted.replace(".", ",")
+                }
             };
 
-            let line = format!(
-                "{}\n{} --> {}\n{}\n\n",
-                i + 1,
-                format_time(start_timestamp as f64 / 100.0),
-                format_time(end_timestamp as f64 / 100.0),
-                segment,
-            );
+            let start = segment_start_secs + start_timestamp as f64 / 100.0;
+            let end = segment_start_secs + end_timestamp as f64 / 100.0;
+
+            let line = if is_vtt {
+                format!(
+                    "{} --> {}\n{}\n\n",
+                    format_time(start),
+                    format_time(end),
+                    segment,
+                )
+            } else {
+                format!(
+                    "{}\n{} --> {}\n{}\n\n",
+                    i + 1,
+                    format_time(start),
+                    format_time(end),
+                    segment,
+                )
+            };
 
             subtitle.push_str(&line);
         }
@@ -137,4 +282,66 @@ mod tests {
         let result = new(Path::new("tests/model/ggml-model-whisper-tiny.bin"), "").await;
         assert!(result.is_ok());
     }
+
+    fn sine_tone(sample_rate: u32, freq: f64, amplitude: f32, num_samples: usize) -> Vec<f32> {
+        (0..num_samples)
+            .map(|i| {
+                let t = i as f64 / sample_rate as f64;
+                amplitude * (2.0 * std::f64::consts::PI * freq * t).sin() as f32
+            })
+            .collect()
+    }
+
+    #[test]
+    fn resample_is_identity_when_rates_match() {
+        let samples = sine_tone(16_000, 440.0, 0.5, 100);
+        assert_eq!(resample(&samples, 16_000, 16_000), samples);
+    }
+
+    #[test]
+    fn resample_scales_length_with_rate_ratio() {
+        let samples = sine_tone(16_000, 440.0, 0.5, 1600);
+        let up = resample(&samples, 16_000, 48_000);
+        assert_eq!(up.len(), 4800);
+        let down = resample(&up, 48_000, 16_000);
+        assert_eq!(down.len(), 1600);
+    }
+
+    #[test]
+    fn denoise_samples_stays_in_normalized_range() {
+        // A clean tone plus a little white noise, at the normalized
+        // [-1.0, 1.0] scale `generate_subtitle` hands to `denoise_samples`.
+        let mut samples = sine_tone(WHISPER_SAMPLE_RATE, 440.0, 0.5, WHISPER_SAMPLE_RATE as usize);
+        for (i, sample) in samples.iter_mut().enumerate() {
+            *sample += 0.05 * ((i as u32).wrapping_mul(2654435761) as f32 / u32::MAX as f32 - 0.5);
+        }
+
+        let denoised = denoise_samples(&samples, WHISPER_SAMPLE_RATE).unwrap();
+
+        assert_eq!(denoised.len(), samples.len());
+        assert!(denoised.iter().all(|s| s.is_finite()));
+        // Forgetting to rescale RNNoise's 16-bit-PCM-range output back down
+        // to [-1.0, 1.0] would leave samples around three orders of
+        // magnitude too large; catch that regression directly.
+        assert!(
+            denoised.iter().all(|&s| s.abs() < 2.0),
+            "denoised samples are out of normalized range, scaling is likely missing"
+        );
+        // And the tone itself should survive denoising, not be gated to silence.
+        let rms = (denoised.iter().map(|s| s * s).sum::<f32>() / denoised.len() as f32).sqrt();
+        assert!(rms > 0.05, "denoised signal collapsed to near-silence: rms={}", rms);
+    }
+
+    #[test]
+    fn normalize_loudness_moves_gain_towards_target() {
+        // A quiet tone, well below TARGET_LUFS, should come out louder.
+        let mut samples = sine_tone(WHISPER_SAMPLE_RATE, 440.0, 0.01, WHISPER_SAMPLE_RATE as usize * 3);
+        let before_peak = samples.iter().fold(0f32, |max, &s| max.max(s.abs()));
+
+        normalize_loudness(&mut samples, WHISPER_SAMPLE_RATE).unwrap();
+
+        let after_peak = samples.iter().fold(0f32, |max, &s| max.max(s.abs()));
+        assert!(after_peak > before_peak, "normalization should raise a quiet signal's gain");
+        assert!(after_peak <= 1.0, "normalized samples must not clip");
+    }
 }